@@ -0,0 +1,20 @@
+// -*- mode: rust; -*-
+//
+// This file is part of curve25519-dalek.
+// Copyright (c) 2016-2021 isis lovecruft
+// Copyright (c) 2016-2019 Henry de Valence
+// See LICENSE for licensing information.
+//
+// Authors:
+// - isis agora lovecruft <isis@patternsinthevoid.net>
+// - Henry de Valence <hdevalence@hdevalence.ca>
+
+//! Implementations of various multiplication algorithms for the backend.
+
+pub mod vartime_double_base;
+
+pub mod straus;
+
+pub mod pippenger;
+
+pub mod precomputed_straus;
@@ -0,0 +1,209 @@
+// -*- mode: rust; -*-
+//
+// This file is part of curve25519-dalek.
+// Copyright (c) 2019 Oleg Andreev
+// See LICENSE for licensing information.
+//
+// Authors:
+// - Oleg Andreev <oleganza@gmail.com>
+#![allow(non_snake_case)]
+
+//! Implementation of the Pippenger, or bucket, method for variable-time
+//! multiscalar multiplication.
+//!
+//! Unlike [`Straus`](super::straus::Straus), which builds one table per point
+//! and always performs ~256 doublings, the bucket method costs roughly
+//! `n + 2^w` additions per window and only `256` doublings in total, making it
+//! `O(n / log n)` and a large win once the number of terms grows into the
+//! hundreds.
+
+use core::borrow::Borrow;
+
+use edwards::EdwardsPoint;
+use scalar::Scalar;
+use traits::Identity;
+
+use super::straus::VartimeMultiscalarMul;
+
+/// Implements a version of Pippenger's algorithm.
+///
+/// See the documentation of [`VartimeMultiscalarMul`] for details on the
+/// interface, and the module documentation for an overview of the algorithm.
+pub struct Pippenger {}
+
+impl VartimeMultiscalarMul for Pippenger {
+    type Point = EdwardsPoint;
+
+    fn optional_multiscalar_mul<I, J>(scalars: I, points: J) -> Option<EdwardsPoint>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<Scalar>,
+        J: IntoIterator<Item = Option<EdwardsPoint>>,
+    {
+        let scalars: Vec<_> = scalars.into_iter().map(|s| *s.borrow()).collect();
+        let points: Vec<_> = points.into_iter().collect();
+        let size = scalars.len();
+
+        assert_eq!(
+            size,
+            points.len(),
+            "number of scalars and points must match"
+        );
+
+        // Digit width in bits. As the size grows, so does the optimal digit
+        // width, trading more buckets for fewer windows.
+        let w = if size < 500 {
+            6
+        } else if size < 800 {
+            7
+        } else {
+            8
+        };
+
+        let max_digit: usize = 1 << w;
+        let digits_count: usize = Scalar::to_radix_2w_size_hint(w);
+        let buckets_count: usize = max_digit / 2; // digits are signed, so we only need half
+
+        // Collect the scalar digits and Niels-form points up front, validating
+        // each point coming out of the (possibly untrusted) iterator.
+        let scalars_points = scalars
+            .iter()
+            .map(|s| s.to_radix_2w(w))
+            .zip(points.into_iter())
+            .map(|(digits, maybe_point)| {
+                maybe_point.map(|point| (digits, point.to_projective_niels()))
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        // Iterate the windows from the most significant to the least.
+        let mut columns = (0..digits_count).rev().map(|digit_index| {
+            // Clear the buckets, since they hold this window's partial sums.
+            let mut buckets: Vec<EdwardsPoint> =
+                (0..buckets_count).map(|_| EdwardsPoint::identity()).collect();
+
+            // Accumulate each point into its bucket for this window, flipping
+            // the sign for negative digits.
+            for (digits, pt) in scalars_points.iter() {
+                let digit = digits[digit_index];
+                if digit > 0 {
+                    let b = (digit - 1) as usize;
+                    buckets[b] = (&buckets[b] + pt).to_extended();
+                } else if digit < 0 {
+                    let b = (-digit - 1) as usize;
+                    buckets[b] = (&buckets[b] - pt).to_extended();
+                }
+            }
+
+            // Add the buckets applying the "summation by parts" trick: starting
+            // from the top bucket, keep a running total and fold it into the
+            // window sum at each step, which yields `sum_k (k + 1) * buckets[k]`
+            // (bucket `k` holds digit `k + 1`) without per-bucket
+            // multiplications.
+            //
+            //   buckets_intermediate_sum = b_{n-1} + b_{n-2} + ... + b_0
+            //   buckets_sum              = b_{n-1} + (b_{n-1} + b_{n-2}) + ...
+            let mut buckets_intermediate_sum = buckets[buckets_count - 1];
+            let mut buckets_sum = buckets[buckets_count - 1];
+            for i in (0..(buckets_count - 1)).rev() {
+                buckets_intermediate_sum += buckets[i];
+                buckets_sum += buckets_intermediate_sum;
+            }
+
+            buckets_sum
+        });
+
+        // Combine the window partials from the most significant window down,
+        // doubling `w` times between windows.
+        let hi_column = columns.next().expect("should have at least one window");
+        Some(columns.fold(hi_column, |total, p| &total.mul_by_pow_2(w as u32) + &p))
+    }
+}
+
+/// The number of terms above which the Pippenger backend beats the
+/// interleaved-NAF (Straus) one. Below this, per-point table construction is
+/// cheap relative to the fixed `2^w` bucket overhead, so Straus wins.
+const VARTIME_PIPPENGER_THRESHOLD: usize = 190;
+
+/// Variable-time multiscalar multiplication on `EdwardsPoint`, dispatching to
+/// the Straus or Pippenger backend based on the number of terms.
+impl VartimeMultiscalarMul for EdwardsPoint {
+    type Point = EdwardsPoint;
+
+    fn optional_multiscalar_mul<I, J>(scalars: I, points: J) -> Option<EdwardsPoint>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<Scalar>,
+        J: IntoIterator<Item = Option<EdwardsPoint>>,
+    {
+        use super::straus::Straus;
+
+        // Collect up front so the dispatch keys on the exact term count: lazy
+        // adaptors (`.filter()`, `.flat_map()`, ...) report a `size_hint` lower
+        // bound of `0`, which would wrongly route a large batch to the slow
+        // Straus path.
+        let scalars: Vec<_> = scalars.into_iter().map(|s| *s.borrow()).collect();
+        let points: Vec<_> = points.into_iter().collect();
+
+        if scalars.len() < VARTIME_PIPPENGER_THRESHOLD {
+            Straus::optional_multiscalar_mul(scalars, points)
+        } else {
+            Pippenger::optional_multiscalar_mul(scalars, points)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use rand::rngs::OsRng;
+
+    use constants;
+    use super::super::straus::Straus;
+
+    /// Naive \\(\sum_i c_i P_i\\) reference, summing one term at a time.
+    fn reference(scalars: &[Scalar], points: &[EdwardsPoint]) -> EdwardsPoint {
+        let mut acc = EdwardsPoint::identity();
+        for (c, P) in scalars.iter().zip(points.iter()) {
+            acc += c * P;
+        }
+        acc
+    }
+
+    /// Check Pippenger against both Straus and the naive reference, with
+    /// mixed-sign scalars, for a batch of `n` terms.
+    fn check(n: usize) {
+        let mut rng = OsRng;
+
+        let scalars: Vec<Scalar> = (0..n)
+            .map(|i| {
+                let c = Scalar::random(&mut rng);
+                // Flip the sign of every third scalar to exercise the negative
+                // digit / subtraction path.
+                if i % 3 == 0 {
+                    -c
+                } else {
+                    c
+                }
+            })
+            .collect();
+        let points: Vec<EdwardsPoint> = (0..n)
+            .map(|_| &Scalar::random(&mut rng) * &constants::ED25519_BASEPOINT_POINT)
+            .collect();
+
+        let pippenger = Pippenger::vartime_multiscalar_mul(scalars.iter(), points.iter());
+        let straus = Straus::vartime_multiscalar_mul(scalars.iter(), points.iter());
+
+        assert_eq!(pippenger, straus);
+        assert_eq!(pippenger, reference(&scalars, &points));
+    }
+
+    #[test]
+    fn vartime_multiscalar_mul_matches_straus_and_reference() {
+        // Sizes straddling the w=6/7/8 digit-width and Straus/Pippenger
+        // dispatch boundaries.
+        for &n in &[1usize, 2, 190, 500, 800] {
+            check(n);
+        }
+    }
+}
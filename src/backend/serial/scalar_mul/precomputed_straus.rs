@@ -0,0 +1,270 @@
+// -*- mode: rust; -*-
+//
+// This file is part of curve25519-dalek.
+// Copyright (c) 2019 isis lovecruft
+// Copyright (c) 2019 Henry de Valence
+// See LICENSE for licensing information.
+//
+// Authors:
+// - isis agora lovecruft <isis@patternsinthevoid.net>
+// - Henry de Valence <hdevalence@hdevalence.ca>
+#![allow(non_snake_case)]
+
+//! Precomputation for Straus's method, for the case where the static points
+//! (e.g. a shared set of public keys, or the basepoint) are reused across many
+//! verifications.
+//!
+//! The `mul` loop rebuilds `NafLookupTable5::from(A)` on every call; the
+//! instrumentation in [`vartime_double_base`](super::vartime_double_base)
+//! already measures table construction as a distinct cost. This type pays that
+//! cost once for the static points and then reuses the tables on every
+//! [`vartime_mixed_multiscalar_mul`](VartimePrecomputedMultiscalarMul::vartime_mixed_multiscalar_mul).
+
+use core::borrow::Borrow;
+
+use backend::serial::curve_models::{ProjectiveNielsPoint, ProjectivePoint};
+use edwards::EdwardsPoint;
+use scalar::Scalar;
+use traits::Identity;
+use window::NafLookupTable5;
+
+/// A trait for variable-time multiscalar multiplication with a set of static
+/// points whose lookup tables are precomputed once and reused.
+pub trait VartimePrecomputedMultiscalarMul: Sized {
+    /// The type of point to be multiplied, e.g., `EdwardsPoint`.
+    type Point;
+
+    /// Given the static points \\( P_i \\), precompute the lookup tables used
+    /// by [`vartime_mixed_multiscalar_mul`](Self::vartime_mixed_multiscalar_mul).
+    fn new<I>(static_points: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Borrow<Self::Point>;
+
+    /// Given `static_scalars` \\( a_i \\), `dynamic_scalars` \\( b_j \\), and
+    /// `dynamic_points` \\( Q_j \\), compute
+    /// $$
+    /// Q = \sum_i a_i P_i + \sum_j b_j Q_j
+    /// $$
+    /// in variable time, where the \\( P_i \\) are the static points given to
+    /// [`new`](Self::new).
+    ///
+    /// If fewer static scalars than static points are given, the \\( i \\)-th
+    /// scalar pairs with the \\( i \\)-th static point and the remaining static
+    /// points do not contribute.
+    ///
+    /// # Panics
+    ///
+    /// If the number of static scalars exceeds the number of static points, or
+    /// if the number of dynamic scalars and points does not match.
+    fn vartime_mixed_multiscalar_mul<I, J, K>(
+        &self,
+        static_scalars: I,
+        dynamic_scalars: J,
+        dynamic_points: K,
+    ) -> Self::Point
+    where
+        I: IntoIterator,
+        I::Item: Borrow<Scalar>,
+        J: IntoIterator,
+        J::Item: Borrow<Scalar>,
+        K: IntoIterator,
+        K::Item: Borrow<Self::Point>;
+}
+
+/// Precomputed Straus multiscalar multiplication over a static set of points.
+pub struct VartimePrecomputedStraus {
+    static_lookup_tables: Vec<NafLookupTable5<ProjectiveNielsPoint>>,
+}
+
+impl VartimePrecomputedMultiscalarMul for VartimePrecomputedStraus {
+    type Point = EdwardsPoint;
+
+    fn new<I>(static_points: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Borrow<EdwardsPoint>,
+    {
+        Self {
+            static_lookup_tables: static_points
+                .into_iter()
+                .map(|P| NafLookupTable5::<ProjectiveNielsPoint>::from(P.borrow()))
+                .collect(),
+        }
+    }
+
+    fn vartime_mixed_multiscalar_mul<I, J, K>(
+        &self,
+        static_scalars: I,
+        dynamic_scalars: J,
+        dynamic_points: K,
+    ) -> EdwardsPoint
+    where
+        I: IntoIterator,
+        I::Item: Borrow<Scalar>,
+        J: IntoIterator,
+        J::Item: Borrow<Scalar>,
+        K: IntoIterator,
+        K::Item: Borrow<EdwardsPoint>,
+    {
+        let static_nafs: Vec<_> = static_scalars
+            .into_iter()
+            .map(|c| c.borrow().non_adjacent_form(5))
+            .collect();
+        let dynamic_nafs: Vec<_> = dynamic_scalars
+            .into_iter()
+            .map(|c| c.borrow().non_adjacent_form(5))
+            .collect();
+
+        assert!(
+            static_nafs.len() <= self.static_lookup_tables.len(),
+            "got more static scalars than static points"
+        );
+
+        // Build fresh tables for the per-call dynamic points; the static tables
+        // are reused from precomputation.
+        let dynamic_lookup_tables: Vec<_> = dynamic_points
+            .into_iter()
+            .map(|P| NafLookupTable5::<ProjectiveNielsPoint>::from(P.borrow()))
+            .collect();
+
+        assert_eq!(
+            dynamic_nafs.len(),
+            dynamic_lookup_tables.len(),
+            "number of dynamic scalars and points must match"
+        );
+
+        // Find the highest nonzero NAF digit across all terms.
+        let mut i: usize = 255;
+        for j in (0..256).rev() {
+            i = j;
+            let any = static_nafs.iter().any(|naf| naf[i] != 0)
+                || dynamic_nafs.iter().any(|naf| naf[i] != 0);
+            if any {
+                break;
+            }
+        }
+
+        let mut r = ProjectivePoint::identity();
+        loop {
+            let mut t = r.double();
+
+            let static_terms = static_nafs.iter().zip(self.static_lookup_tables.iter());
+            let dynamic_terms = dynamic_nafs.iter().zip(dynamic_lookup_tables.iter());
+            for (naf, table) in static_terms.chain(dynamic_terms) {
+                if naf[i] > 0 {
+                    t = &t.to_extended() + &table.select(naf[i] as usize);
+                } else if naf[i] < 0 {
+                    t = &t.to_extended() - &table.select(-naf[i] as usize);
+                }
+            }
+
+            r = t.to_projective();
+
+            if i == 0 {
+                break;
+            }
+            i -= 1;
+        }
+
+        r.to_extended()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use rand::rngs::OsRng;
+
+    use constants;
+    use super::super::straus::{Straus, VartimeMultiscalarMul};
+
+    fn random_points(n: usize, rng: &mut OsRng) -> Vec<EdwardsPoint> {
+        (0..n)
+            .map(|_| &Scalar::random(rng) * &constants::ED25519_BASEPOINT_POINT)
+            .collect()
+    }
+
+    fn random_scalars(n: usize, rng: &mut OsRng) -> Vec<Scalar> {
+        (0..n).map(|_| Scalar::random(rng)).collect()
+    }
+
+    /// The mixed result must equal a plain Straus over the concatenated
+    /// static∥dynamic terms.
+    fn check_matches_straus(static_used: usize, n_static: usize, n_dynamic: usize) {
+        let mut rng = OsRng;
+
+        let static_points = random_points(n_static, &mut rng);
+        let dynamic_points = random_points(n_dynamic, &mut rng);
+        let static_scalars = random_scalars(static_used, &mut rng);
+        let dynamic_scalars = random_scalars(n_dynamic, &mut rng);
+
+        let precomputed = VartimePrecomputedStraus::new(static_points.iter());
+        let result = precomputed.vartime_mixed_multiscalar_mul(
+            &static_scalars,
+            &dynamic_scalars,
+            dynamic_points.iter(),
+        );
+
+        // Only the first `static_used` static points contribute (the surplus
+        // tables are dropped by the `zip`).
+        let scalars: Vec<Scalar> = static_scalars
+            .iter()
+            .chain(dynamic_scalars.iter())
+            .cloned()
+            .collect();
+        let points: Vec<EdwardsPoint> = static_points[..static_used]
+            .iter()
+            .chain(dynamic_points.iter())
+            .cloned()
+            .collect();
+        let reference = Straus::vartime_multiscalar_mul(scalars.iter(), points.iter());
+
+        assert_eq!(result, reference);
+    }
+
+    #[test]
+    fn mixed_matches_straus() {
+        check_matches_straus(4, 4, 3);
+    }
+
+    #[test]
+    fn fewer_static_scalars_than_points() {
+        // Documented behavior: a short static-scalar slice uses only a prefix
+        // of the precomputed points.
+        check_matches_straus(2, 4, 3);
+    }
+
+    #[test]
+    #[should_panic]
+    fn more_static_scalars_than_points_panics() {
+        let mut rng = OsRng;
+        let static_points = random_points(2, &mut rng);
+        let static_scalars = random_scalars(3, &mut rng);
+
+        let precomputed = VartimePrecomputedStraus::new(static_points.iter());
+        let _ = precomputed.vartime_mixed_multiscalar_mul(
+            &static_scalars,
+            Vec::<Scalar>::new().iter(),
+            Vec::<EdwardsPoint>::new().iter(),
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn mismatched_dynamic_counts_panic() {
+        let mut rng = OsRng;
+        let static_points = random_points(2, &mut rng);
+        let static_scalars = random_scalars(2, &mut rng);
+        let dynamic_scalars = random_scalars(3, &mut rng);
+        let dynamic_points = random_points(2, &mut rng);
+
+        let precomputed = VartimePrecomputedStraus::new(static_points.iter());
+        let _ = precomputed.vartime_mixed_multiscalar_mul(
+            &static_scalars,
+            &dynamic_scalars,
+            dynamic_points.iter(),
+        );
+    }
+}
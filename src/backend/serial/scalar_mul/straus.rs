@@ -0,0 +1,180 @@
+// -*- mode: rust; -*-
+//
+// This file is part of curve25519-dalek.
+// Copyright (c) 2016-2021 isis lovecruft
+// Copyright (c) 2016-2019 Henry de Valence
+// See LICENSE for licensing information.
+//
+// Authors:
+// - isis agora lovecruft <isis@patternsinthevoid.net>
+// - Henry de Valence <hdevalence@hdevalence.ca>
+#![allow(non_snake_case)]
+
+//! Implementation of the interleaved window, or Straus', method for
+//! variable-time multiscalar multiplication.
+//!
+//! This is the natural generalization of the two-term `aA + bB` loop used by
+//! [`vartime_double_base::mul`](super::vartime_double_base::mul) to an
+//! arbitrary number of `(Scalar, EdwardsPoint)` terms: one width-5
+//! non-adjacent form and one [`NafLookupTable5`] are built per input point,
+//! and a single running accumulator is doubled once per bit while every
+//! term's digit is folded in.
+
+use core::borrow::Borrow;
+
+use backend::serial::curve_models::{ProjectiveNielsPoint, ProjectivePoint};
+use edwards::EdwardsPoint;
+use scalar::Scalar;
+use traits::Identity;
+use window::NafLookupTable5;
+
+/// A trait for variable-time multiscalar multiplication without precomputation.
+pub trait VartimeMultiscalarMul {
+    /// The type of point being multiplied, e.g., `EdwardsPoint`.
+    type Point;
+
+    /// Given an iterator of scalars and an iterator of points, compute
+    /// $$
+    /// Q = c_1 P_1 + \cdots + c_n P_n
+    /// $$
+    /// in variable time.
+    ///
+    /// This function is the foundation for the rest of the trait; the points
+    /// are wrapped in `Option`s so that inputs coming from untrusted
+    /// decompression can be validated lazily. If any of the `points` is
+    /// `None`, it returns `None`.
+    fn optional_multiscalar_mul<I, J>(scalars: I, points: J) -> Option<Self::Point>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<Scalar>,
+        J: IntoIterator<Item = Option<Self::Point>>;
+
+    /// Given an iterator of scalars and an iterator of points, compute
+    /// $$
+    /// Q = c_1 P_1 + \cdots + c_n P_n
+    /// $$
+    /// in variable time.
+    ///
+    /// # Panics
+    ///
+    /// If the number of scalars and points does not match.
+    fn vartime_multiscalar_mul<I, J>(scalars: I, points: J) -> Self::Point
+    where
+        I: IntoIterator,
+        I::Item: Borrow<Scalar>,
+        J: IntoIterator,
+        J::Item: Borrow<Self::Point>,
+        Self::Point: Clone,
+    {
+        Self::optional_multiscalar_mul(
+            scalars,
+            points.into_iter().map(|P| Some(P.borrow().clone())),
+        )
+        .expect("should return some point")
+    }
+}
+
+/// An interleaved-window, or Straus', multiscalar multiplication using
+/// width-5 non-adjacent forms.
+pub struct Straus {}
+
+impl VartimeMultiscalarMul for Straus {
+    type Point = EdwardsPoint;
+
+    fn optional_multiscalar_mul<I, J>(scalars: I, points: J) -> Option<EdwardsPoint>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<Scalar>,
+        J: IntoIterator<Item = Option<EdwardsPoint>>,
+    {
+        let nafs: Vec<_> = scalars
+            .into_iter()
+            .map(|c| c.borrow().non_adjacent_form(5))
+            .collect();
+        let lookup_tables = points
+            .into_iter()
+            .map(|P_opt| P_opt.map(|P| NafLookupTable5::<ProjectiveNielsPoint>::from(&P)))
+            .collect::<Option<Vec<_>>>()?;
+
+        assert_eq!(
+            nafs.len(),
+            lookup_tables.len(),
+            "number of scalars and points must match"
+        );
+
+        // Find the highest nonzero NAF digit across all terms, so we can skip
+        // the leading all-zero bits just as the two-term loop does.
+        let mut i: usize = 255;
+        for j in (0..256).rev() {
+            i = j;
+            if nafs.iter().any(|naf| naf[i] != 0) {
+                break;
+            }
+        }
+
+        let mut r = ProjectivePoint::identity();
+        loop {
+            let mut t = r.double();
+
+            for (naf, table) in nafs.iter().zip(lookup_tables.iter()) {
+                if naf[i] > 0 {
+                    t = &t.to_extended() + &table.select(naf[i] as usize);
+                } else if naf[i] < 0 {
+                    t = &t.to_extended() - &table.select(-naf[i] as usize);
+                }
+            }
+
+            r = t.to_projective();
+
+            if i == 0 {
+                break;
+            }
+            i -= 1;
+        }
+
+        Some(r.to_extended())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use rand::rngs::OsRng;
+
+    use constants;
+
+    #[test]
+    fn vartime_multiscalar_mul_matches_reference() {
+        let mut rng = OsRng;
+        let n = 16;
+
+        let scalars: Vec<Scalar> = (0..n).map(|_| Scalar::random(&mut rng)).collect();
+        let points: Vec<EdwardsPoint> = (0..n)
+            .map(|_| &Scalar::random(&mut rng) * &constants::ED25519_BASEPOINT_POINT)
+            .collect();
+
+        // Iterated single-term reference.
+        let mut reference = EdwardsPoint::identity();
+        for (c, P) in scalars.iter().zip(points.iter()) {
+            reference += c * P;
+        }
+
+        let result = Straus::vartime_multiscalar_mul(scalars.iter(), points.iter());
+        assert_eq!(result, reference);
+    }
+
+    #[test]
+    fn optional_multiscalar_mul_returns_none_on_missing_point() {
+        let mut rng = OsRng;
+
+        let scalars: Vec<Scalar> = (0..3).map(|_| Scalar::random(&mut rng)).collect();
+        let points = vec![
+            Some(&Scalar::random(&mut rng) * &constants::ED25519_BASEPOINT_POINT),
+            None,
+            Some(&Scalar::random(&mut rng) * &constants::ED25519_BASEPOINT_POINT),
+        ];
+
+        assert!(Straus::optional_multiscalar_mul(scalars.iter(), points.into_iter()).is_none());
+    }
+}